@@ -1,25 +1,144 @@
 use std::marker::PhantomData;
 
 use halo2_proofs::{
-    circuit::{Layouter, SimpleFloorPlanner, Value},
-    pasta::group::ff::PrimeField,
-    plonk::{self, Advice, Circuit, Column, ConstraintSystem, Expression, Selector, TableColumn},
-    poly::Rotation,
+    circuit::{Layouter, Region, SimpleFloorPlanner, Value},
+    dev,
+    pasta::{group::ff::PrimeField, Eq, EqAffine, Fp},
+    plonk::{
+        self, create_proof, keygen_pk, keygen_vk, verify_proof, Advice, Circuit, Column,
+        ConstraintSystem, Expression, Instance, Selector, SingleVerifier, TableColumn,
+    },
+    poly::{commitment::Params, Rotation},
+    transcript::{Blake2bRead, Blake2bWrite, Challenge255},
 };
+use rand_core::OsRng;
+
+/// Per-symbol delta (+1 open, -1 close, 0 empty) and its matching bracket,
+/// i.e. the char that must close it if `c` is an opening bracket. Symbols
+/// outside the supported alphabet map to `(0, '\0')`, which the lookup
+/// argument in `configure` rejects.
+fn bracket_info<F: PrimeField>(c: char) -> (F, F) {
+    match c {
+        '(' => (F::ONE, F::from(')' as u64)),
+        ')' => (-F::ONE, F::from('(' as u64)),
+        '[' => (F::ONE, F::from(']' as u64)),
+        ']' => (-F::ONE, F::from('[' as u64)),
+        '{' => (F::ONE, F::from('}' as u64)),
+        '}' => (-F::ONE, F::from('{' as u64)),
+        _ => (F::ZERO, F::ZERO),
+    }
+}
 
 // Sets the circuit, and also stores the private input
-pub struct BracketCircuit<const L: usize, F: PrimeField> {
-    input: [char; L],
+#[derive(Clone)]
+pub struct BracketCircuit<F: PrimeField> {
+    input: Vec<Value<char>>,
+    // Runtime shape of the circuit: the maximum bracket-string length this
+    // proving/verifying key pair can serve. Strings shorter than `max_len`
+    // are padded with the empty symbol up to this length.
+    max_len: usize,
     _p: PhantomData<F>,
 }
 
-impl<const L: usize, F: PrimeField> BracketCircuit<L, F> {
-    pub fn new(input: [char; L]) -> Self {
+// `stack` is base-`STACK_BASE` field arithmetic decomposed into only
+// `STACK_DIGITS` (= `MAX_NESTING_DEPTH`) digits, so it can only encode stacks
+// up to `MAX_NESTING_DEPTH` (see its doc comment below); shared by both
+// constructors so the rationale lives in exactly one place.
+fn check_max_len(max_len: usize) {
+    assert!(
+        max_len <= MAX_NESTING_DEPTH,
+        "max_len {} exceeds MAX_NESTING_DEPTH {}: the Horner-encoded \
+         `stack` column has only that many range-checked digits",
+        max_len,
+        MAX_NESTING_DEPTH
+    );
+}
+
+impl<F: PrimeField> BracketCircuit<F> {
+    pub fn new(input: &[char], max_len: usize) -> Self {
+        assert!(
+            input.len() <= max_len,
+            "input of length {} exceeds max_len {}",
+            input.len(),
+            max_len
+        );
+        check_max_len(max_len);
+
+        let mut padded: Vec<Value<char>> = input.iter().copied().map(Value::known).collect();
+        padded.resize(max_len, Value::known('\0'));
+
         Self {
-            input,
+            input: padded,
+            max_len,
             _p: PhantomData,
         }
     }
+
+    fn blank(max_len: usize) -> Self {
+        check_max_len(max_len);
+
+        Self {
+            input: vec![Value::unknown(); max_len],
+            max_len,
+            _p: PhantomData,
+        }
+    }
+}
+
+// Base of the Horner encoding `stack` below. Characters are restricted by the
+// lookup table to ASCII bracket codes (< 128), so digits never overflow it.
+const STACK_BASE: u64 = 256;
+
+// Hard cap on simultaneously-pending open brackets (and hence on `max_len`,
+// which bounds it). `stack` is base-`STACK_BASE` field arithmetic, so it is
+// only injective while
+// `STACK_BASE.pow(depth)` stays under the field modulus: Pallas/Vesta's `Fp`
+// is a ~254-bit prime, i.e. `log_256(p) ≈ 31.8`, so depth 32 already has
+// `256^32 > p` and, by pigeonhole, guarantees colliding (and therefore
+// forgeable) stack encodings. 30 keeps `256^30 = 2^240` comfortably below the
+// modulus with margin to spare; the circuit simply refuses to key a
+// `max_len` above that rather than rely on callers never trying a larger one.
+const MAX_NESTING_DEPTH: usize = 30;
+
+// Width of the range check that keeps `stack` a genuine base-`STACK_BASE`
+// digit decomposition. `check_max_len` only bounds how deep an *honest*
+// stack can legitimately go; on its own it does nothing to stop a prover
+// from solving the `pop` relation below for a `stack` value that doesn't
+// correspond to any real digit sequence at all (e.g. two mismatched
+// close brackets whose algebraic errors happen to cancel: see the
+// `sibling_brackets_with_swapped_closers_are_rejected` regression test).
+// Forcing `stack` to decompose into `STACK_DIGITS` bytes, each range
+// checked via a lookup, rules that out: `STACK_BASE^STACK_DIGITS` is
+// comfortably below the field modulus (see `MAX_NESTING_DEPTH`'s doc
+// comment), so a forged, effectively-random field element essentially
+// never happens to fall inside the valid decomposition range.
+const STACK_DIGITS: usize = MAX_NESTING_DEPTH;
+
+// Little-endian base-`STACK_BASE` digits of `v`, read off its canonical byte
+// representation. Only meaningful on values within
+// `[0, STACK_BASE^STACK_DIGITS)`, which every legitimately constructed `stack`
+// value is; for any other value (as `synthesize` computes for a mismatched
+// string) the digits do not recompose to `v`, so the `stack_in_range` gate
+// fails. See `STACK_DIGITS`.
+fn stack_digits<F: PrimeField>(v: F) -> [F; STACK_DIGITS] {
+    let repr = v.to_repr();
+    let bytes = repr.as_ref();
+    std::array::from_fn(|i| F::from(bytes[i] as u64))
+}
+
+// Assigns `value`'s `STACK_DIGITS` base-`STACK_BASE` digits at `row`, for the
+// `stack_in_range` gate to check against the `stack` cell assigned there.
+fn assign_stack_digits<F: PrimeField>(
+    region: &mut Region<'_, F>,
+    digit_cols: &[Column<Advice>],
+    row: usize,
+    value: Value<F>,
+) -> Result<(), plonk::Error> {
+    let digits = value.map(stack_digits);
+    for (i, &col) in digit_cols.iter().enumerate() {
+        region.assign_advice(|| "stack digit", col, row, || digits.map(|d| d[i]))?;
+    }
+    Ok(())
 }
 
 // Stores the configuration of the table (columns) that the circuit needs
@@ -27,72 +146,172 @@ impl<const L: usize, F: PrimeField> BracketCircuit<L, F> {
 pub struct Config {
     s_input: Selector,
     s_not_minus_one: Selector,
-    s_is_accum_zero: Selector,
     // For input
     input: Column<Advice>,
-    // For allowed ASCII codes
-    allowed: TableColumn,
+    // Per-row +1/-1/0 contribution to `accum`, and its matching bracket (the
+    // expected closer for an opening bracket, the opener for a closing one).
+    delta: Column<Advice>,
+    partner: Column<Advice>,
+    // Allowed (char, delta, partner) triples.
+    allowed_char: TableColumn,
+    allowed_delta: TableColumn,
+    allowed_partner: TableColumn,
     accum: Column<Advice>,
     inverted_accum_plus_1: Column<Advice>,
+    // Horner encoding, base `STACK_BASE`, of the partner chars still pending
+    // on the bracket stack (bottom digit first assigned). Pushing an opening
+    // bracket appends its expected closer as the new top digit; popping a
+    // closing bracket removes and checks it against the top digit. Unlike
+    // keying solely on `accum` (bracket depth), this ties each closer to the
+    // exact opener it matches, not merely to openers at the same depth.
+    stack: Column<Advice>,
+    // Base-`STACK_BASE` digits of `stack` (see `STACK_DIGITS`), each range
+    // checked against `allowed_byte`. Forces `stack` to actually be a valid
+    // digit sequence rather than an arbitrary field element a malicious
+    // prover could otherwise solve the `pop` relation for.
+    stack_digit: Vec<Column<Advice>>,
+    allowed_byte: TableColumn,
+    // Public input: the accumulator value the string is expected to end on.
+    // A balanced string binds this to zero.
+    instance: Column<Instance>,
 }
 
-impl<const L: usize, F: PrimeField> Circuit<F> for BracketCircuit<L, F> {
+impl<F: PrimeField> Circuit<F> for BracketCircuit<F> {
     type Config = Config;
 
     // Not important at this stage
     type FloorPlanner = SimpleFloorPlanner;
 
     fn without_witnesses(&self) -> Self {
-        todo!("Not needed at this stage.")
+        Self::blank(self.max_len)
     }
 
+    // The gates don't depend on `max_len`: the same Config serves any length up
+    // to the one the keygen circuit was shaped with, since `synthesize` always
+    // walks exactly `max_len` rows (see `BracketCircuit::new`). `max_len` is
+    // plain runtime state on `BracketCircuit` itself (see `new`/`blank`), not
+    // something `configure` needs to see.
     fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
         let config = Config {
             s_input: meta.selector(),
-            s_is_accum_zero: meta.selector(),
             s_not_minus_one: meta.selector(),
             input: meta.advice_column(),
+            delta: meta.advice_column(),
+            partner: meta.advice_column(),
+            allowed_char: meta.lookup_table_column(),
+            allowed_delta: meta.lookup_table_column(),
+            allowed_partner: meta.lookup_table_column(),
             accum: meta.advice_column(),
             inverted_accum_plus_1: meta.advice_column(),
-            allowed: meta.lookup_table_column(),
+            stack: meta.advice_column(),
+            stack_digit: (0..STACK_DIGITS).map(|_| meta.advice_column()).collect(),
+            allowed_byte: meta.lookup_table_column(),
+            instance: meta.instance_column(),
         };
 
-        // f(x) = 81 - 2*input
-        meta.create_gate("accumulation", |meta| {
-            let _81 = Expression::Constant(F::from(81));
-            let _2 = Expression::Constant(F::from(2));
+        meta.enable_equality(config.accum);
+        meta.enable_equality(config.instance);
+        meta.enable_equality(config.stack);
 
+        // accum[next] = accum[cur] + delta, where delta is bound to `input` by
+        // the `allowed` lookup below.
+        meta.create_gate("accumulation", |meta| {
             let s_input = meta.query_selector(config.s_input);
-            let s_is_accum_zero = meta.query_selector(config.s_is_accum_zero);
-            let input = meta.query_advice(config.input, Rotation::cur());
+            let delta = meta.query_advice(config.delta, Rotation::cur());
             let prev = meta.query_advice(config.accum, Rotation::cur());
             let result = meta.query_advice(config.accum, Rotation::next());
 
-            vec![
-                s_input * (prev.clone() + (_81 - _2 * input) - result),
-                s_is_accum_zero * prev,
-            ]
+            vec![s_input * (prev + delta - result)]
         });
 
         meta.create_gate("check_accum", |meta| {
-            let _1 = Expression::Constant(F::ONE);
+            let one = Expression::Constant(F::ONE);
 
             let s = meta.query_selector(config.s_not_minus_one);
             let accum = meta.query_advice(config.accum, Rotation::cur());
             let inv_x = meta.query_advice(config.inverted_accum_plus_1, Rotation::cur());
 
-            let x = accum + _1.clone();
+            let x = accum + one.clone();
 
-            let gate1 = _1 - (x.clone() * inv_x);
+            let gate1 = one - (x.clone() * inv_x);
             let gate2 = x * gate1.clone();
 
             vec![s.clone() * gate1, s * gate2]
         });
 
-        meta.lookup(|table| {
-            let input = table.query_advice(config.input, Rotation::cur());
+        // Type-matching argument: `stack` is a base-`STACK_BASE` Horner
+        // encoding of the partner chars still pending on the bracket stack.
+        // An opening bracket pushes its expected closer as the new top digit
+        // (`stack' = stack * BASE + partner`); a closing bracket pops and
+        // checks the top digit against the char actually seen
+        // (`stack = stack' * BASE + input`); any other row leaves `stack`
+        // unchanged. `delta` is restricted by the lookup to {-1, 0, 1}, so
+        // `(delta^2 + delta)/2` and `(delta^2 - delta)/2` are exactly the
+        // is-opening / is-closing indicators, and `1` minus their sum is the
+        // is-empty indicator.
+        meta.create_gate("type_stack", |meta| {
+            let s_input = meta.query_selector(config.s_input);
+
+            let input = meta.query_advice(config.input, Rotation::cur());
+            let delta = meta.query_advice(config.delta, Rotation::cur());
+            let partner = meta.query_advice(config.partner, Rotation::cur());
+            let stack_cur = meta.query_advice(config.stack, Rotation::cur());
+            let stack_next = meta.query_advice(config.stack, Rotation::next());
+
+            let one = Expression::Constant(F::ONE);
+            let half = Expression::Constant(F::from(2).invert().unwrap());
+            let base = Expression::Constant(F::from(STACK_BASE));
+
+            let is_open = (delta.clone() * delta.clone() + delta.clone()) * half.clone();
+            let is_close = (delta.clone() * delta.clone() - delta) * half;
+            let is_empty = one - is_open.clone() - is_close.clone();
+
+            let push = stack_next.clone() - stack_cur.clone() * base.clone() - partner;
+            let pop = stack_cur.clone() - stack_next.clone() * base - input;
+            let stay = stack_next - stack_cur;
+
+            vec![s_input * (is_open * push + is_close * pop + is_empty * stay)]
+        });
+
+        // `stack` must equal the base-`STACK_BASE` number its own
+        // `stack_digit` columns spell out, and each digit is range checked
+        // via the lookup below. Gated by `s_not_minus_one`, which (like
+        // `stack` itself) is enabled on every row from the initial state
+        // through the final one, i.e. exactly the rows where `stack` holds
+        // a value that needs to actually be a digit sequence.
+        meta.create_gate("stack_in_range", |meta| {
+            let s = meta.query_selector(config.s_not_minus_one);
+            let stack = meta.query_advice(config.stack, Rotation::cur());
+
+            let recomposed = config.stack_digit.iter().enumerate().fold(
+                Expression::Constant(F::ZERO),
+                |acc, (i, &digit_col)| {
+                    let digit = meta.query_advice(digit_col, Rotation::cur());
+                    let place_value = Expression::Constant(F::from(STACK_BASE).pow([i as u64]));
+                    acc + digit * place_value
+                },
+            );
+
+            vec![s * (stack - recomposed)]
+        });
+
+        for &digit_col in &config.stack_digit {
+            meta.lookup(|meta| {
+                let digit = meta.query_advice(digit_col, Rotation::cur());
+                vec![(digit, config.allowed_byte)]
+            });
+        }
+
+        meta.lookup(|meta| {
+            let input = meta.query_advice(config.input, Rotation::cur());
+            let delta = meta.query_advice(config.delta, Rotation::cur());
+            let partner = meta.query_advice(config.partner, Rotation::cur());
 
-            vec![(input, config.allowed)]
+            vec![
+                (input, config.allowed_char),
+                (delta, config.allowed_delta),
+                (partner, config.allowed_partner),
+            ]
         });
 
         config
@@ -106,77 +325,271 @@ impl<const L: usize, F: PrimeField> Circuit<F> for BracketCircuit<L, F> {
         layouter.assign_table(
             || "allowed",
             |mut table| {
-                table.assign_cell(|| "empty", config.allowed, 0, || Value::known(F::ZERO))?;
-                table.assign_cell(
-                    || "(",
-                    config.allowed,
-                    1,
-                    || Value::known(F::from('(' as u64)),
-                )?;
-                table.assign_cell(
-                    || ")",
-                    config.allowed,
-                    2,
-                    || Value::known(F::from(')' as u64)),
-                )?;
+                let rows: [(char, F, char); 7] = [
+                    ('\0', F::ZERO, '\0'),
+                    ('(', F::ONE, ')'),
+                    (')', -F::ONE, '('),
+                    ('[', F::ONE, ']'),
+                    (']', -F::ONE, '['),
+                    ('{', F::ONE, '}'),
+                    ('}', -F::ONE, '{'),
+                ];
+
+                for (offset, (c, delta, partner)) in rows.into_iter().enumerate() {
+                    table.assign_cell(
+                        || "char",
+                        config.allowed_char,
+                        offset,
+                        || Value::known(F::from(c as u64)),
+                    )?;
+                    table.assign_cell(
+                        || "delta",
+                        config.allowed_delta,
+                        offset,
+                        || Value::known(delta),
+                    )?;
+                    table.assign_cell(
+                        || "partner",
+                        config.allowed_partner,
+                        offset,
+                        || Value::known(F::from(partner as u64)),
+                    )?;
+                }
 
                 Ok(())
             },
         )?;
 
-        layouter.assign_region(
+        layouter.assign_table(
+            || "allowed byte",
+            |mut table| {
+                for byte in 0..STACK_BASE {
+                    table.assign_cell(
+                        || "byte",
+                        config.allowed_byte,
+                        byte as usize,
+                        || Value::known(F::from(byte)),
+                    )?;
+                }
+
+                Ok(())
+            },
+        )?;
+
+        let base = F::from(STACK_BASE);
+        let base_inv = Value::known(base.invert().unwrap());
+
+        let last_accum = layouter.assign_region(
             || "",
             |mut region| {
-                let _81 = Value::known(F::from(81));
-                let _2 = Value::known(F::from(2));
-
-                let prev =
+                let accum0 =
                     region.assign_advice(|| "accum", config.accum, 0, || Value::known(F::ZERO))?;
-
                 region.assign_advice(
                     || "inverted accum",
                     config.inverted_accum_plus_1,
                     0,
-                    || prev.value().map(|val| val.add(F::ONE).invert().unwrap()),
+                    || accum0.value().map(|val| val.add(F::ONE).invert().unwrap()),
                 )?;
                 config.s_not_minus_one.enable(&mut region, 0)?;
 
-                self.input
-                    .iter()
-                    .map(|sym| Value::known(F::from(*sym as u64)))
-                    .enumerate()
-                    .try_fold(prev.value().copied(), |prev, (offset, sym)| {
+                let stack0 =
+                    region.assign_advice(|| "stack", config.stack, 0, || Value::known(F::ZERO))?;
+                let stack0_cell = stack0.cell();
+                assign_stack_digits(
+                    &mut region,
+                    &config.stack_digit,
+                    0,
+                    Value::known(F::ZERO),
+                )?;
+
+                let (accum_final, stack_final) = self.input.iter().enumerate().try_fold(
+                    (accum0, stack0),
+                    |(accum_prev, stack_prev), (offset, sym)| {
                         config.s_input.enable(&mut region, offset)?;
 
-                        region.assign_advice(|| "input", config.input, offset, || sym)?;
+                        let char_val = sym.map(|c| F::from(c as u64));
+                        let (delta_val, partner_val) = {
+                            let info = sym.map(bracket_info::<F>);
+                            (info.map(|(d, _)| d), info.map(|(_, p)| p))
+                        };
 
-                        let acc_value = _81 - (_2 * sym) + prev;
+                        region.assign_advice(|| "input", config.input, offset, || char_val)?;
+                        region.assign_advice(|| "delta", config.delta, offset, || delta_val)?;
+                        region.assign_advice(
+                            || "partner",
+                            config.partner,
+                            offset,
+                            || partner_val,
+                        )?;
+
+                        let accum_prev_val = accum_prev.value().copied();
+                        let accum_value = accum_prev_val + delta_val;
 
                         config.s_not_minus_one.enable(&mut region, offset + 1)?;
-                        region.assign_advice(|| "accum", config.accum, offset + 1, || acc_value)?;
+                        let accum_next = region.assign_advice(
+                            || "accum",
+                            config.accum,
+                            offset + 1,
+                            || accum_value,
+                        )?;
                         region.assign_advice(
                             || "inv_accum",
                             config.inverted_accum_plus_1,
                             offset + 1,
-                            || acc_value.map(|v| v.add(F::ONE).invert().unwrap_or_else(|| F::ZERO)),
+                            || {
+                                accum_value
+                                    .map(|v| v.add(F::ONE).invert().unwrap_or_else(|| F::ZERO))
+                            },
                         )?;
 
-                        Result::<_, plonk::Error>::Ok(acc_value)
-                    })?;
+                        let half = F::from(2).invert().unwrap();
+                        let is_open = (delta_val * delta_val + delta_val) * Value::known(half);
+                        let is_close = (delta_val * delta_val - delta_val) * Value::known(half);
+                        let is_empty = Value::known(F::ONE) - is_open - is_close;
 
-                //config.s_is_accum_zero.enable(&mut region, L)?;
+                        let stack_prev_val = stack_prev.value().copied();
+                        let push = stack_prev_val * Value::known(base) + partner_val;
+                        let pop = (stack_prev_val - char_val) * base_inv;
+                        let stay = stack_prev_val;
 
-                Ok(())
+                        let stack_value = is_open * push + is_close * pop + is_empty * stay;
+                        let stack_next = region.assign_advice(
+                            || "stack",
+                            config.stack,
+                            offset + 1,
+                            || stack_value,
+                        )?;
+                        assign_stack_digits(
+                            &mut region,
+                            &config.stack_digit,
+                            offset + 1,
+                            stack_value,
+                        )?;
+
+                        Result::<_, plonk::Error>::Ok((accum_next, stack_next))
+                    },
+                )?;
+
+                // The stack must land back on its initial (empty) encoding:
+                // every opening bracket's pushed digit was popped by the
+                // closing bracket that actually followed it, so the bracket
+                // types nest correctly and not just their depths.
+                region.constrain_equal(stack_final.cell(), stack0_cell)?;
+
+                Ok(accum_final)
             },
         )?;
 
+        // Binds the final accumulator value to the public instance, so the verifier
+        // can check whether the (private) string was balanced without seeing it.
+        layouter.constrain_instance(last_accum.cell(), config.instance, 0)?;
+
         Ok(())
     }
 }
 
+/// Runs the full keygen + proving pipeline for a bracket string padded to
+/// `max_len` and returns the serialized proof. `instance` is the final
+/// accumulator value the prover is publicly committing to (zero for a
+/// balanced string; padding has delta 0 and does not change it).
+pub fn prove(params: &Params<EqAffine>, input: &[char], max_len: usize, instance: Fp) -> Vec<u8> {
+    let circuit = BracketCircuit::<Fp>::new(input, max_len);
+
+    let vk = keygen_vk(params, &circuit).expect("keygen_vk should not fail");
+    let pk = keygen_pk(params, vk, &circuit).expect("keygen_pk should not fail");
+
+    let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+    create_proof(
+        params,
+        &pk,
+        &[circuit],
+        &[&[&[instance]]],
+        OsRng,
+        &mut transcript,
+    )
+    .expect("proof generation should not fail");
+    transcript.finalize()
+}
+
+/// Verifies a proof produced by [`prove`] against the claimed `instance` value.
+/// `max_len` must match the value the proof was created with.
+pub fn verify(params: &Params<EqAffine>, proof: &[u8], max_len: usize, instance: Fp) -> bool {
+    let blank = BracketCircuit::<Fp>::blank(max_len);
+    let vk = keygen_vk(params, &blank).expect("keygen_vk should not fail");
+
+    let strategy = SingleVerifier::new(params);
+    let mut transcript = Blake2bRead::<_, EqAffine, Challenge255<_>>::init(proof);
+    verify_proof(params, &vk, strategy, &[&[&[instance]]], &mut transcript).is_ok()
+}
+
+// Number of columns `configure` allocates, kept here so
+// `estimate_cost` doesn't need to reconstruct a `ConstraintSystem` just to
+// count them.
+// input, delta, partner, accum, inverted_accum_plus_1, stack, stack_digit (one per digit)
+const ADVICE_COLUMNS: usize = 6 + STACK_DIGITS;
+// s_input, s_not_minus_one, allowed_char, allowed_delta, allowed_partner, allowed_byte
+const FIXED_COLUMNS: usize = 6;
+// the input/delta/partner lookup, plus one per stack digit's range check
+const LOOKUP_ARGUMENTS: usize = 1 + STACK_DIGITS;
+
+/// Resource usage of a [`BracketCircuit`] sized for `max_len` at a given `k`:
+/// row/column counts plus the proof size `halo2_proofs::dev::cost` estimates
+/// for a single proof. `rows_used` counts only the witness region (row 0 plus
+/// one row per symbol); the lookup tables are not included in it, and the
+/// `allowed_byte` table alone fills `STACK_BASE` (256) rows.
+#[derive(Clone, Copy, Debug)]
+pub struct CostEstimate {
+    pub k: u32,
+    pub max_len: usize,
+    pub rows_used: usize,
+    pub advice_columns: usize,
+    pub fixed_columns: usize,
+    pub lookup_arguments: usize,
+    pub proof_size_bytes: usize,
+}
+
+pub fn estimate_cost(k: u32, max_len: usize) -> CostEstimate {
+    let circuit = BracketCircuit::<Fp>::blank(max_len);
+    // `CircuitCost::measure` wants the curve's projective group type, not its
+    // affine form `EqAffine` used everywhere else in this file for keygen/
+    // proving/verifying.
+    let cost = dev::cost::CircuitCost::<Eq, BracketCircuit<Fp>>::measure(k, &circuit);
+
+    CostEstimate {
+        k,
+        max_len,
+        // Row 0 holds the starting accumulator; one more row per symbol.
+        rows_used: max_len + 1,
+        advice_columns: ADVICE_COLUMNS,
+        fixed_columns: FIXED_COLUMNS,
+        lookup_arguments: LOOKUP_ARGUMENTS,
+        proof_size_bytes: usize::from(cost.proof_size(1)),
+    }
+}
+
+/// Renders the [`CircuitLayout`](dev::CircuitLayout) of a `max_len`-sized
+/// `BracketCircuit` into `root`, showing how the input/accum/inverted_accum
+/// advice columns, the lookup table, and the selectors lay out as `max_len`
+/// grows. Requires the `dev-graph` feature.
+#[cfg(feature = "dev-graph")]
+pub fn render_layout<DB: plotters::prelude::DrawingBackend>(
+    k: u32,
+    max_len: usize,
+    root: &plotters::prelude::DrawingArea<DB, plotters::coord::Shift>,
+) -> Result<(), Box<dyn std::error::Error + 'static>>
+where
+    DB::ErrorType: 'static,
+{
+    let circuit = BracketCircuit::<Fp>::blank(max_len);
+    dev::CircuitLayout::default()
+        .show_labels(true)
+        .render(k, &circuit, root)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use halo2_proofs::{dev::MockProver, pasta::Fq};
+    use halo2_proofs::{dev::MockProver, pasta::group::ff::Field, pasta::Fp};
 
     use super::*;
 
@@ -184,25 +597,149 @@ mod tests {
 
     #[test]
     fn unvalid_sym() {
-        MockProver::run(K, &BracketCircuit::<1, Fq>::new(['*']), vec![])
-            .unwrap()
-            .verify()
-            .unwrap_err();
+        MockProver::run(
+            K,
+            &BracketCircuit::<Fp>::new(&['*'], 1),
+            vec![vec![Fp::ZERO]],
+        )
+        .unwrap()
+        .verify()
+        .unwrap_err();
     }
 
     #[test]
     fn valid_1() {
-        MockProver::run(K, &BracketCircuit::<2, Fq>::new(['(', ')']), vec![])
-            .unwrap()
-            .verify()
-            .unwrap();
+        MockProver::run(
+            K,
+            &BracketCircuit::<Fp>::new(&['(', ')'], 2),
+            vec![vec![Fp::ZERO]],
+        )
+        .unwrap()
+        .verify()
+        .unwrap();
     }
 
     #[test]
     fn unvalid_order() {
-        MockProver::run(K, &BracketCircuit::<2, Fq>::new([')', '(']), vec![])
-            .unwrap()
-            .verify()
-            .unwrap_err();
+        MockProver::run(
+            K,
+            &BracketCircuit::<Fp>::new(&[')', '('], 2),
+            vec![vec![Fp::ZERO]],
+        )
+        .unwrap()
+        .verify()
+        .unwrap_err();
+    }
+
+    #[test]
+    fn unbalanced_does_not_match_zero_instance() {
+        MockProver::run(
+            K,
+            &BracketCircuit::<Fp>::new(&['(', '('], 2),
+            vec![vec![Fp::ZERO]],
+        )
+        .unwrap()
+        .verify()
+        .unwrap_err();
+    }
+
+    #[test]
+    fn shorter_than_max_len_is_padded() {
+        // `max_len` fixes the key's shape; a 2-char string can still be proved
+        // against a key sized for up to 4 chars. The empty padding symbol has
+        // delta 0, so it leaves the accumulator (and the instance) untouched.
+        MockProver::run(
+            K,
+            &BracketCircuit::<Fp>::new(&['(', ')'], 4),
+            vec![vec![Fp::ZERO]],
+        )
+        .unwrap()
+        .verify()
+        .unwrap();
+    }
+
+    #[test]
+    fn mixed_bracket_types_nest_correctly() {
+        MockProver::run(
+            K,
+            &BracketCircuit::<Fp>::new(&['(', '[', ']', ')'], 4),
+            vec![vec![Fp::ZERO]],
+        )
+        .unwrap()
+        .verify()
+        .unwrap();
+    }
+
+    #[test]
+    fn mismatched_bracket_types_are_rejected() {
+        // Well-nested by depth, but "(" is closed by "]" and "[" by ")": the
+        // type-matching argument must catch this even though the
+        // counter-based depth check alone would accept it.
+        MockProver::run(
+            K,
+            &BracketCircuit::<Fp>::new(&['(', '[', ')', ']'], 4),
+            vec![vec![Fp::ZERO]],
+        )
+        .unwrap()
+        .verify()
+        .unwrap_err();
+    }
+
+    #[test]
+    fn sibling_brackets_with_swapped_closers_are_rejected() {
+        // Both brackets sit at depth 0->1->0, i.e. the same depth as each
+        // other, but "(" is closed by "}" and "{" by ")": a matching argument
+        // keyed on depth alone (rather than the specific opener/closer pair)
+        // would wrongly accept this.
+        MockProver::run(
+            K,
+            &BracketCircuit::<Fp>::new(&['(', '}', '{', ')'], 4),
+            vec![vec![Fp::ZERO]],
+        )
+        .unwrap()
+        .verify()
+        .unwrap_err();
+    }
+
+    #[test]
+    fn proof_roundtrip() {
+        let params = Params::<EqAffine>::new(K);
+        let input = ['(', '[', ']', ')'];
+
+        let proof = prove(&params, &input, 4, Fp::ZERO);
+        assert!(verify(&params, &proof, 4, Fp::ZERO));
+    }
+
+    #[test]
+    fn tampered_proof_fails() {
+        let params = Params::<EqAffine>::new(K);
+        let input = ['(', ')'];
+
+        let mut proof = prove(&params, &input, 2, Fp::ZERO);
+        *proof.last_mut().unwrap() ^= 0xff;
+        assert!(!verify(&params, &proof, 2, Fp::ZERO));
+    }
+
+    #[test]
+    fn cost_grows_with_max_len() {
+        let small = estimate_cost(K, 4);
+        let large = estimate_cost(K, MAX_NESTING_DEPTH);
+
+        assert_eq!(small.advice_columns, large.advice_columns);
+        assert_eq!(small.rows_used, 5);
+        assert_eq!(large.rows_used, MAX_NESTING_DEPTH + 1);
+        assert!(large.proof_size_bytes >= small.proof_size_bytes);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds MAX_NESTING_DEPTH")]
+    fn nesting_depth_beyond_safe_horner_capacity_is_rejected() {
+        // 32 unmatched opens is exactly the depth at which `256^depth`
+        // overflows Fp's modulus, guaranteeing (by pigeonhole) a colliding
+        // `stack` encoding for some pair of distinct strings. The circuit
+        // must refuse to key a `max_len` that large rather than silently
+        // accept an unsound key.
+        let opens = vec!['('; 32];
+        let _ = BracketCircuit::<Fp>::new(&opens, 32);
     }
 }